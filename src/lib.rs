@@ -1,13 +1,35 @@
 use crate::typemap::TypeMap;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::ops::Deref;
+use std::pin::Pin;
 use std::sync::Arc;
 
+pub mod sync;
 pub mod typemap;
 
+/// A lazy factory, type-erased so providers of every type can share one map.
+///
+/// The two lifetimes are independent on purpose: a provider only needs a
+/// shared borrow of *some* injector, regardless of how long that injector's
+/// own parent borrow lives.
+type Provider = Box<dyn for<'s, 'i> Fn(&'s Injector<'i>) -> Box<dyn Any>>;
+
 #[derive(Default)]
 pub struct Injector<'a> {
     values: TypeMap,
+    /// Factories registered with [`Injector::add_provider`], keyed by the type
+    /// they produce.
+    providers: RefCell<HashMap<TypeId, Provider>>,
+    /// Memoized provider outputs. Each value lives in its own `Box`, and
+    /// entries are only ever added (never moved or removed) while the injector
+    /// is alive, so we can safely hand out `&T` through a shared borrow.
+    built: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+    /// Types whose provider is currently being resolved, used to turn a
+    /// dependency cycle into a clear panic instead of unbounded recursion.
+    in_progress: RefCell<HashSet<TypeId>>,
     parent: Option<&'a mut Injector<'a>>,
 }
 
@@ -19,6 +41,9 @@ impl<'a> Injector<'a> {
     pub fn with_parent(self, parent: &'a mut Injector<'a>) -> Self {
         Self {
             values: self.values,
+            providers: self.providers,
+            built: self.built,
+            in_progress: self.in_progress,
             parent: Some(parent),
         }
     }
@@ -27,16 +52,100 @@ impl<'a> Injector<'a> {
         self.values.insert(v);
     }
 
-    pub fn get<T: 'static>(&self) -> Option<&T> {
-        if let Some(v) = self.values.get::<T>() {
+    /// Register a lazy factory for `T`.
+    ///
+    /// Instead of a fully-constructed value, `add_provider` stores a closure
+    /// that builds `T` from other injected dependencies. The first `get::<T>()`
+    /// that misses runs the factory — resolving its own arguments through this
+    /// same injector, exactly like [`Injector::call`] — then memoizes the
+    /// result, so `T` behaves as a lazily-initialized singleton.
+    pub fn add_provider<A, T, F>(&mut self, f: F)
+    where
+        T: 'static,
+        F: CallInjector<A, T> + 'static,
+    {
+        let provider: Provider = Box::new(move |inj| Box::new(f.call(inj)) as Box<dyn Any>);
+        self.providers.borrow_mut().insert(TypeId::of::<T>(), provider);
+    }
+
+    /// Bind a concrete implementation under a trait-object interface.
+    ///
+    /// The value is stored keyed by `TypeId::of::<Dyn>()`, so callers can
+    /// depend on the abstraction `&Dyn` via [`Injector::get_dyn`] and swap
+    /// implementations (real vs. mock) by changing only the binding:
+    ///
+    /// ```ignore
+    /// injector.bind::<dyn Repository>(Box::new(PostgresRepo::new()));
+    /// let repo: &dyn Repository = injector.get_dyn::<dyn Repository>().unwrap();
+    /// ```
+    pub fn bind<Dyn: ?Sized + 'static>(&mut self, v: Box<Dyn>) {
+        self.values.insert_dyn(v);
+    }
+
+    /// Resolve a trait object previously registered with [`Injector::bind`].
+    pub fn get_dyn<Dyn: ?Sized + 'static>(&self) -> Option<&Dyn> {
+        if let Some(v) = self.values.get_dyn::<Dyn>() {
             Some(v)
         } else if let Some(p) = &self.parent {
+            p.get_dyn::<Dyn>()
+        } else {
+            None
+        }
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        if let Some(v) = self.values.get::<T>() {
+            return Some(v);
+        }
+        if let Some(v) = self.get_built::<T>() {
+            return Some(v);
+        }
+        if self.providers.borrow().contains_key(&TypeId::of::<T>()) {
+            self.build::<T>();
+            return self.get_built::<T>();
+        }
+        if let Some(p) = &self.parent {
             p.get::<T>()
         } else {
             None
         }
     }
 
+    /// Look up an already-built provider value.
+    fn get_built<T: 'static>(&self) -> Option<&T> {
+        let built = self.built.borrow();
+        let value: &T = built
+            .get(&TypeId::of::<T>())
+            .and_then(|boxed| (&**boxed as &(dyn Any + 'static)).downcast_ref())?;
+        // SAFETY: the value is owned by a `Box` in `self.built`, which is never
+        // moved out of or removed from while `self` is borrowed, so the pointee
+        // stays valid and pinned for as long as the returned reference.
+        Some(unsafe { &*(value as *const T) })
+    }
+
+    /// Run the registered provider for `T` and cache its output.
+    fn build<T: 'static>(&self) {
+        let id = TypeId::of::<T>();
+        if !self.in_progress.borrow_mut().insert(id) {
+            panic!(
+                "tapioca: cyclic provider dependency while resolving `{}`",
+                std::any::type_name::<T>()
+            );
+        }
+        // Borrow the providers map only to invoke the factory; the factory
+        // itself resolves its dependencies through `self`, which re-borrows
+        // these cells immutably, so nothing is held across a conflicting borrow.
+        let value = {
+            let providers = self.providers.borrow();
+            let provider = providers
+                .get(&id)
+                .expect("build called without a registered provider");
+            provider(self)
+        };
+        self.built.borrow_mut().insert(id, value);
+        self.in_progress.borrow_mut().remove(&id);
+    }
+
     pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
         if let Some(v) = self.values.get_mut::<T>() {
             Some(v)
@@ -50,19 +159,61 @@ impl<'a> Injector<'a> {
     pub fn call<A, F: CallInjector<A, R>, R>(&self, f: F) -> R {
         CallInjector::call(&f, self)
     }
+
+    /// Resolve the arguments of an async handler from this injector and return
+    /// the handler's future, boxed and tied to the injector borrow.
+    ///
+    /// The returned future borrows the values it resolved out of the injector,
+    /// so it may not outlive the `&self` borrow taken here.
+    pub fn call_async<'i, A, F, Output>(
+        &'i self,
+        f: F,
+    ) -> Pin<Box<dyn Future<Output = Output> + 'i>>
+    where
+        F: AsyncCallInjector<'i, A, Output>,
+    {
+        AsyncCallInjector::call(&f, self)
+    }
+}
+
+/// How a single handler argument is resolved out of an [`Injector`].
+///
+/// There is a blanket impl for every concrete `'static` type (resolved by
+/// value through [`Injector::get`]). Trait-object parameters are `!Sized`, so
+/// they fall outside that blanket: register an implementation alongside each
+/// bound interface to resolve it through [`Injector::get_dyn`], e.g.
+///
+/// ```ignore
+/// impl Resolve for dyn Repository {
+///     fn resolve<'i>(injector: &'i Injector) -> Option<&'i Self> {
+///         injector.get_dyn::<dyn Repository>()
+///     }
+/// }
+/// ```
+pub trait Resolve {
+    fn resolve<'i>(injector: &'i Injector) -> Option<&'i Self>;
+}
+
+impl<T: 'static> Resolve for T {
+    fn resolve<'i>(injector: &'i Injector) -> Option<&'i T> {
+        injector.get::<T>()
+    }
 }
 
 /// Handler converter factory
-/// Async handler converter factory
 pub trait CallInjector<Args, Res>: Clone {
     fn call(&self, i: &Injector) -> Res;
 }
 
-pub trait AsyncCallInjector<Args, Res, Output>: Clone + 'static
-where
-    Res: Future<Output = Output>,
-{
-    fn call(&self, i: &Injector) -> Res;
+/// Async handler converter factory.
+///
+/// The generated impls resolve each argument from the `Injector` and hand the
+/// borrowed references to the handler. Because the handler's future may borrow
+/// those references, it is boxed and tied to the `'i` injector borrow — the
+/// returned future may not outlive the injector it was resolved from, which is
+/// exactly the guarantee an async web handler needs.
+pub trait AsyncCallInjector<'i, Args, Output>: Clone {
+    fn call<'p>(&self, i: &'i Injector<'p>) -> Pin<Box<dyn Future<Output = Output> + 'i>>;
 }
 
 impl<F, Res> CallInjector<(), Res> for F
@@ -74,33 +225,34 @@ where
     }
 }
 
-impl<F, Res, Output> AsyncCallInjector<(), Res, Output> for F
+impl<'i, F, Fut, Output> AsyncCallInjector<'i, (), Output> for F
 where
-    Res: Future<Output = Output>,
-    F: Fn() -> Res + Clone + 'static,
+    Fut: Future<Output = Output> + 'i,
+    F: Fn() -> Fut + Clone,
 {
-    fn call(&self, _: &Injector) -> Res {
-        (self)()
+    fn call<'p>(&self, _: &'i Injector<'p>) -> Pin<Box<dyn Future<Output = Output> + 'i>> {
+        Box::pin((self)())
     }
 }
 
 macro_rules! factory_tuple ({ $(($n:tt, $T:ident)),+} => {
-        impl<Func, $($T: 'static,)+ Res> CallInjector<($($T,)+), Res> for Func
+        impl<Func, $($T: ?Sized + 'static,)+ Res> CallInjector<($(*const $T,)+), Res> for Func
         where Func: Fn($(&$T,)+) -> Res + Clone,
+              $($T: Resolve,)+
         {
             fn call(&self, inj: &Injector) -> Res {
-                (self)($(inj.get::<$T>().unwrap(),)+)
+                (self)($(<$T as Resolve>::resolve(inj).unwrap(),)+)
             }
         }
 
-        // impl<Func, $($T,)+ Res, Output> AsyncCallInjector<($($T,)+), Res, Output> for Func
-        // where Func: Fn($($T,)+) -> Res + Clone + 'static,
-        //       Res: Future<Output = Output>,
-        // {
-        //     fn call(&self, param: ($($T,)+)) -> Res {
-        //         (self)($(param.$n,)+)
-        //     }
-        // }
+        impl<'i, Func, $($T: 'static,)+ Fut, Output> AsyncCallInjector<'i, ($($T,)+), Output> for Func
+        where Func: Fn($(&'i $T,)+) -> Fut + Clone,
+              Fut: Future<Output = Output> + 'i,
+        {
+            fn call<'p>(&self, inj: &'i Injector<'p>) -> Pin<Box<dyn Future<Output = Output> + 'i>> {
+                Box::pin((self)($(inj.get::<$T>().unwrap(),)+))
+            }
+        }
     });
 
 #[rustfmt::skip]
@@ -122,8 +274,28 @@ mod m {
 #[cfg(test)]
 mod tests {
     use crate::Injector;
+    use std::future::Future;
     use std::sync::{Arc, Mutex};
 
+    /// Minimal std-only executor: the handlers under test are ready on first
+    /// poll, so a busy loop with a no-op waker is enough to drive them.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = Box::pin(fut);
+        loop {
+            if let Poll::Ready(v) = fut.as_mut().poll(&mut cx) {
+                return v;
+            }
+        }
+    }
+
     #[test]
     fn it_works() {
         fn plus_one(a: &u32) -> u32 {
@@ -182,10 +354,82 @@ mod tests {
 
         let mut i1 = Injector::default();
         i1.add_value(5u32);
-        let mut i2 = Injector::default().with_parent(&mut i1);
-        i1.add_value(5u32);
+        i1.add_value(80u8);
+        i1.add_value(100u16);
+        let i2 = Injector::default().with_parent(&mut i1);
 
-        assert_eq!(i.call(plus), 85);
-        assert_eq!(i.call(plus_3), 185);
+        assert_eq!(i2.call(plus), 85);
+        assert_eq!(i2.call(plus_3), 185);
+    }
+
+    #[test]
+    fn call_async_resolves_borrowing_handler() {
+        async fn plus_one(a: &u32) -> u32 {
+            a + 1
+        }
+
+        let mut i = Injector::default();
+        i.add_value(10u32);
+
+        assert_eq!(block_on(i.call_async(plus_one)), 11);
+    }
+
+    #[test]
+    fn provider_builds_lazily_and_memoizes() {
+        #[derive(PartialEq, Debug)]
+        struct Doubled(u32);
+
+        let mut i = Injector::default();
+        i.add_value(21u32);
+        i.add_provider(|a: &u32| Doubled(a * 2));
+
+        assert_eq!(i.get::<Doubled>(), Some(&Doubled(42)));
+        // Same instance is returned on subsequent accesses.
+        assert_eq!(i.get::<Doubled>(), Some(&Doubled(42)));
+        assert!(std::ptr::eq(
+            i.get::<Doubled>().unwrap(),
+            i.get::<Doubled>().unwrap()
+        ));
+    }
+
+    #[test]
+    fn binds_and_resolves_by_interface() {
+        use crate::Resolve;
+
+        trait Greeter {
+            fn greet(&self) -> String;
+        }
+        struct English;
+        impl Greeter for English {
+            fn greet(&self) -> String {
+                "hello".to_string()
+            }
+        }
+        impl Resolve for dyn Greeter {
+            fn resolve<'i>(injector: &'i Injector) -> Option<&'i Self> {
+                injector.get_dyn::<dyn Greeter>()
+            }
+        }
+
+        fn greet(g: &(dyn Greeter + 'static)) -> String {
+            g.greet()
+        }
+
+        let mut i = Injector::default();
+        i.bind::<dyn Greeter>(Box::new(English));
+
+        assert_eq!(i.get_dyn::<dyn Greeter>().unwrap().greet(), "hello");
+        assert_eq!(i.call(greet), "hello");
+    }
+
+    #[test]
+    #[should_panic(expected = "cyclic provider dependency")]
+    fn provider_cycle_panics() {
+        struct A(());
+
+        let mut i = Injector::default();
+        i.add_provider(|_a: &A| A(()));
+
+        i.get::<A>();
     }
 }
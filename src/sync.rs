@@ -0,0 +1,161 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A type-erased entry. Each value sits behind its own `RwLock` so that
+/// distinct types can be read and mutated concurrently without contending on a
+/// single global lock. The `Arc` lets a guard keep an entry alive after the
+/// outer map lock has been released.
+type Entry = Arc<RwLock<Box<dyn Any + Send + Sync>>>;
+
+/// A `Send + Sync` injector that can be shared across threads and async tasks.
+///
+/// Modeled on `tracing`'s `Extensions`: the type-keyed map lives behind an
+/// `Arc<RwLock<_>>`, and each stored value behind a per-entry `RwLock`. Clone
+/// the injector to share the same backing store (the `Arc` is shared, not the
+/// data), then use [`SyncInjector::get`]/[`SyncInjector::get_mut`] to obtain a
+/// read or write guard for a single type at a time.
+#[derive(Clone, Default)]
+pub struct SyncInjector {
+    map: Arc<RwLock<HashMap<TypeId, Entry>>>,
+}
+
+impl SyncInjector {
+    /// Create an empty `SyncInjector`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a value, replacing any previous value of the same type.
+    pub fn add_value<T: Any + Send + Sync>(&self, v: T) {
+        let entry: Entry = Arc::new(RwLock::new(Box::new(v)));
+        self.map.write().unwrap().insert(TypeId::of::<T>(), entry);
+    }
+
+    /// Check whether a value of type `T` is registered.
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.map.read().unwrap().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Acquire a read guard over the stored value of type `T`.
+    ///
+    /// Returns `None` if no value of that type was registered. The guard holds
+    /// a read lock on just that entry, so other types remain writable.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<Ref<T>> {
+        let entry = self.map.read().unwrap().get(&TypeId::of::<T>())?.clone();
+        Some(Ref::new(entry))
+    }
+
+    /// Acquire a write guard over the stored value of type `T`.
+    ///
+    /// Returns `None` if no value of that type was registered.
+    pub fn get_mut<T: Any + Send + Sync>(&self) -> Option<RefMut<T>> {
+        let entry = self.map.read().unwrap().get(&TypeId::of::<T>())?.clone();
+        Some(RefMut::new(entry))
+    }
+}
+
+/// A read guard returned by [`SyncInjector::get`] that dereferences to `T`.
+pub struct Ref<T: 'static> {
+    // Declared before `_entry` so the guard is dropped first, releasing the
+    // lock before the entry it borrows from.
+    guard: RwLockReadGuard<'static, Box<dyn Any + Send + Sync>>,
+    _entry: Entry,
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> Ref<T> {
+    fn new(entry: Entry) -> Self {
+        // SAFETY: the guard borrows from the `RwLock` owned by `entry`. We move
+        // `entry` into the returned value and drop the guard before it, so the
+        // lock outlives the guard and extending the borrow to `'static` is sound.
+        let guard: RwLockReadGuard<'static, Box<dyn Any + Send + Sync>> =
+            unsafe { std::mem::transmute(entry.read().unwrap()) };
+        Ref {
+            guard,
+            _entry: entry,
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> Deref for Ref<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (&**self.guard as &(dyn Any + Send + Sync))
+            .downcast_ref::<T>()
+            .expect("SyncInjector entry has mismatched type")
+    }
+}
+
+/// A write guard returned by [`SyncInjector::get_mut`] that dereferences to `T`.
+pub struct RefMut<T: 'static> {
+    guard: RwLockWriteGuard<'static, Box<dyn Any + Send + Sync>>,
+    _entry: Entry,
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> RefMut<T> {
+    fn new(entry: Entry) -> Self {
+        // SAFETY: see `Ref::new`; the guard is dropped before `_entry`, so the
+        // `'static` lifetime never outlives the lock it points at.
+        let guard: RwLockWriteGuard<'static, Box<dyn Any + Send + Sync>> =
+            unsafe { std::mem::transmute(entry.write().unwrap()) };
+        RefMut {
+            guard,
+            _entry: entry,
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> Deref for RefMut<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (&**self.guard as &(dyn Any + Send + Sync))
+            .downcast_ref::<T>()
+            .expect("SyncInjector entry has mismatched type")
+    }
+}
+
+impl<T: 'static> DerefMut for RefMut<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        (&mut **self.guard as &mut (dyn Any + Send + Sync))
+            .downcast_mut::<T>()
+            .expect("SyncInjector entry has mismatched type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyncInjector;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn reads_and_writes_a_value() {
+        let i = SyncInjector::new();
+        i.add_value(40u32);
+
+        *i.get_mut::<u32>().unwrap() += 2;
+        assert_eq!(*i.get::<u32>().unwrap(), 42);
+        assert!(i.get::<u8>().is_none());
+    }
+
+    #[test]
+    fn shares_across_threads_when_cloned() {
+        let i = SyncInjector::new();
+        i.add_value(Arc::new(0u32));
+        i.add_value(String::from("shared"));
+
+        let clone = i.clone();
+        let handle = thread::spawn(move || clone.get::<String>().unwrap().len());
+
+        assert_eq!(handle.join().unwrap(), 6);
+        assert_eq!(*i.get::<String>().unwrap(), "shared");
+    }
+}
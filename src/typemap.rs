@@ -1,17 +1,69 @@
 use std::any::{Any, TypeId};
+#[cfg(not(feature = "fast-map"))]
 use std::collections::BTreeMap;
+#[cfg(feature = "fast-map")]
+use std::collections::HashMap;
+#[cfg(feature = "fast-map")]
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// A `Hasher` that passes the single integer written to it straight through.
+///
+/// `TypeId`'s own `Hash` implementation writes exactly one integer (a `u64`
+/// on older toolchains, a `u128` on newer ones), and that integer is already
+/// a high-quality hash. Re-mixing it would only cost cycles, so this hasher
+/// stores a written `u64` verbatim (a `u128` is folded to 64 bits by XOR-ing
+/// its halves) and returns the stored value unchanged from `finish`.
+#[cfg(feature = "fast-map")]
+#[derive(Default)]
+pub struct IdHasher(u64);
+
+#[cfg(feature = "fast-map")]
+impl Hasher for IdHasher {
+    #[inline]
+    fn write(&mut self, _bytes: &[u8]) {
+        // `TypeId` hashes itself by writing a single integer, so the byte-slice
+        // path is unused. No-op rather than panic: a future encoding that wrote
+        // bytes should degrade to a poor-but-correct hash, not crash the map.
+    }
+
+    #[inline]
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+
+    #[inline]
+    fn write_u128(&mut self, n: u128) {
+        // Newer `TypeId` encodings write a `u128`; fold the halves together so
+        // `finish` can still hand back a single `u64` without any mixing.
+        self.0 = (n as u64) ^ ((n >> 64) as u64);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(feature = "fast-map")]
+type Backing = HashMap<TypeId, Box<dyn Any>, BuildHasherDefault<IdHasher>>;
+#[cfg(not(feature = "fast-map"))]
+type Backing = BTreeMap<TypeId, Box<dyn Any>>;
 
 #[derive(Default)]
 /// A map of Type => Instance.
-/// We use a BTreeMap here to allow for determinism. The number of insertion/removal isn't expected
-/// to be high.
-pub struct TypeMap(BTreeMap<TypeId, Box<dyn Any>>);
+///
+/// By default we use a `BTreeMap` to allow for determinism; the number of
+/// insertions/removals isn't expected to be high. When the `fast-map` feature
+/// is enabled the backing store becomes a `HashMap` keyed with a pass-through
+/// [`IdHasher`], turning a lookup into a single probe at the cost of ordered
+/// iteration.
+pub struct TypeMap(Backing);
 
 impl TypeMap {
     /// Create an empty `TypeMap`.
     #[inline]
     pub fn new() -> TypeMap {
-        TypeMap(BTreeMap::default())
+        TypeMap(Backing::default())
     }
 
     /// Insert a type into this `TypeMap`.
@@ -22,6 +74,24 @@ impl TypeMap {
         self.0.insert(TypeId::of::<T>(), Box::new(val));
     }
 
+    /// Insert a value under the `TypeId` of an (unsized) trait object.
+    ///
+    /// The boxed trait object is itself a `'static` sized value, so it can be
+    /// stored as `dyn Any` and later recovered with [`TypeMap::get_dyn`],
+    /// keyed by the interface type rather than the concrete implementation.
+    pub fn insert_dyn<Dyn: ?Sized + 'static>(&mut self, val: Box<Dyn>) {
+        self.0.insert(TypeId::of::<Dyn>(), Box::new(val));
+    }
+
+    /// Get a reference to a trait object previously inserted with
+    /// [`TypeMap::insert_dyn`].
+    pub fn get_dyn<Dyn: ?Sized + 'static>(&self) -> Option<&Dyn> {
+        self.0
+            .get(&TypeId::of::<Dyn>())
+            .and_then(|boxed| (&**boxed as &(dyn Any + 'static)).downcast_ref::<Box<Dyn>>())
+            .map(|boxed| &**boxed)
+    }
+
     /// Check if container contains entry
     pub fn contains<T: 'static>(&self) -> bool {
         self.0.get(&TypeId::of::<T>()).is_some()